@@ -1,17 +1,18 @@
 use crate::model::{GraphName, NamedOrBlankNode};
 use crate::sparql::algebra::DatasetSpec;
 use crate::sparql::EvaluationError;
+#[cfg(feature = "rdf-star")]
+use crate::store::numeric_encoder::EncodedTriple;
 use crate::store::numeric_encoder::{
     EncodedQuad, EncodedTerm, ReadEncoder, StrContainer, StrEncodingAware, StrId, StrLookup,
 };
 use crate::store::ReadableEncodedStore;
-use lasso::{Rodeo, Spur};
-use std::cell::RefCell;
+use lasso::{Spur, ThreadedRodeo};
 use std::iter::empty;
 
 pub(crate) struct DatasetView<S: ReadableEncodedStore> {
     store: S,
-    extra: RefCell<Rodeo>,
+    extra: ThreadedRodeo,
     default_graph_as_union: bool,
     dataset: Option<EncodedDatasetSpec<S::StrId>>,
 }
@@ -61,12 +62,16 @@ impl<S: ReadableEncodedStore> DatasetView<S> {
         };
         Ok(Self {
             store,
-            extra: RefCell::new(Rodeo::default()),
+            extra: ThreadedRodeo::default(),
             default_graph_as_union,
             dataset,
         })
     }
+}
 
+// `Clone` lets `LazyGraphUnionIter` hold an owned store handle instead of
+// borrowing `&self`, so opening a graph's cursor can stay deferred to `next()`.
+impl<S: ReadableEncodedStore + Clone> DatasetView<S> {
     fn encoded_quads_for_pattern_in_dataset(
         &self,
         subject: Option<EncodedTerm<S::StrId>>,
@@ -78,27 +83,24 @@ impl<S: ReadableEncodedStore> DatasetView<S> {
         if let Some(dataset) = &self.dataset {
             if let Some(graph_name) = graph_name {
                 if graph_name == EncodedTerm::DefaultGraph {
-                    let iters = dataset
-                        .default
-                        .iter()
-                        .map(|graph_name| {
-                            self.store.encoded_quads_for_pattern(
-                                subject,
-                                predicate,
-                                object,
-                                Some(*graph_name),
-                            )
-                        })
-                        .collect::<Vec<_>>();
-                    Box::new(map_iter(iters.into_iter().flatten()).map(|quad| {
-                        let quad = quad?;
-                        Ok(EncodedQuad::new(
-                            quad.subject,
-                            quad.predicate,
-                            quad.object,
-                            EncodedTerm::DefaultGraph,
+                    Box::new(
+                        map_iter(LazyGraphUnionIter::new(
+                            &self.store,
+                            subject,
+                            predicate,
+                            object,
+                            dataset.default.clone(),
                         ))
-                    }))
+                        .map(|quad| {
+                            let quad = quad?;
+                            Ok(EncodedQuad::new(
+                                quad.subject,
+                                quad.predicate,
+                                quad.object,
+                                EncodedTerm::DefaultGraph,
+                            ))
+                        }),
+                    )
                 } else if dataset.named.contains(&graph_name) {
                     Box::new(map_iter(self.store.encoded_quads_for_pattern(
                         subject,
@@ -110,19 +112,13 @@ impl<S: ReadableEncodedStore> DatasetView<S> {
                     Box::new(empty())
                 }
             } else {
-                let iters = dataset
-                    .named
-                    .iter()
-                    .map(|graph_name| {
-                        self.store.encoded_quads_for_pattern(
-                            subject,
-                            predicate,
-                            object,
-                            Some(*graph_name),
-                        )
-                    })
-                    .collect::<Vec<_>>();
-                Box::new(map_iter(iters.into_iter().flatten()))
+                Box::new(map_iter(LazyGraphUnionIter::new(
+                    &self.store,
+                    subject,
+                    predicate,
+                    object,
+                    dataset.named.clone(),
+                )))
             }
         } else if graph_name == None {
             Box::new(
@@ -152,14 +148,12 @@ impl<S: ReadableEncodedStore> StrLookup for DatasetView<S> {
     fn get_str(&self, id: DatasetStrId<S::StrId>) -> Result<Option<String>, EvaluationError> {
         match id {
             DatasetStrId::Store(id) => self.store.get_str(id).map_err(|e| e.into()),
-            DatasetStrId::Temporary(id) => {
-                Ok(self.extra.borrow().try_resolve(&id).map(|e| e.to_owned()))
-            }
+            DatasetStrId::Temporary(id) => Ok(self.extra.try_resolve(&id).map(|e| e.to_owned())),
         }
     }
 
     fn get_str_id(&self, value: &str) -> Result<Option<DatasetStrId<S::StrId>>, EvaluationError> {
-        if let Some(id) = self.extra.borrow().get(value) {
+        if let Some(id) = self.extra.get(value) {
             Ok(Some(DatasetStrId::Temporary(id)))
         } else {
             Ok(self
@@ -171,7 +165,7 @@ impl<S: ReadableEncodedStore> StrLookup for DatasetView<S> {
     }
 }
 
-impl<S: ReadableEncodedStore> ReadableEncodedStore for DatasetView<S> {
+impl<S: ReadableEncodedStore + Clone> ReadableEncodedStore for DatasetView<S> {
     type QuadsIter =
         Box<dyn Iterator<Item = Result<EncodedQuad<DatasetStrId<S::StrId>>, EvaluationError>>>;
 
@@ -207,20 +201,109 @@ impl<S: ReadableEncodedStore> ReadableEncodedStore for DatasetView<S> {
     }
 }
 
+/// Chains the per-graph quad scans of a `FROM`/`FROM NAMED` dataset lazily, only
+/// opening the next graph's store cursor once the current one is exhausted, so a
+/// short-circuiting consumer (`ASK`, `LIMIT 1`) never pays for graphs it never
+/// reaches. Results are grouped graph-by-graph rather than globally ordered across
+/// graphs: doing the latter would mean pulling at least one row from every graph
+/// up front to find the true minimum, which defeats the laziness this exists for.
+struct LazyGraphUnionIter<S: ReadableEncodedStore> {
+    store: S,
+    subject: Option<EncodedTerm<S::StrId>>,
+    predicate: Option<EncodedTerm<S::StrId>>,
+    object: Option<EncodedTerm<S::StrId>>,
+    remaining_graphs: std::vec::IntoIter<EncodedTerm<S::StrId>>,
+    current: Option<S::QuadsIter>,
+}
+
+impl<S: ReadableEncodedStore + Clone> LazyGraphUnionIter<S> {
+    fn new(
+        store: &S,
+        subject: Option<EncodedTerm<S::StrId>>,
+        predicate: Option<EncodedTerm<S::StrId>>,
+        object: Option<EncodedTerm<S::StrId>>,
+        graphs: Vec<EncodedTerm<S::StrId>>,
+    ) -> Self {
+        Self {
+            store: store.clone(),
+            subject,
+            predicate,
+            object,
+            remaining_graphs: graphs.into_iter(),
+            current: None,
+        }
+    }
+}
+
+impl<S: ReadableEncodedStore + Clone> Iterator for LazyGraphUnionIter<S> {
+    type Item = Result<EncodedQuad<S::StrId>, S::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(current) = &mut self.current {
+                if let Some(quad) = current.next() {
+                    return Some(quad);
+                }
+                self.current = None;
+            }
+            let graph_name = self.remaining_graphs.next()?;
+            self.current = Some(self.store.encoded_quads_for_pattern(
+                self.subject,
+                self.predicate,
+                self.object,
+                Some(graph_name),
+            ));
+        }
+    }
+}
+
 fn map_iter<'a, I: StrId>(
     iter: impl Iterator<Item = Result<EncodedQuad<I>, impl Into<EvaluationError>>> + 'a,
 ) -> impl Iterator<Item = Result<EncodedQuad<DatasetStrId<I>>, EvaluationError>> + 'a {
     iter.map(|t| {
         t.map(|q| EncodedQuad {
-            subject: q.subject.map_id(DatasetStrId::Store),
-            predicate: q.predicate.map_id(DatasetStrId::Store),
-            object: q.object.map_id(DatasetStrId::Store),
-            graph_name: q.graph_name.map_id(DatasetStrId::Store),
+            subject: map_term(q.subject, DatasetStrId::Store),
+            predicate: map_term(q.predicate, DatasetStrId::Store),
+            object: map_term(q.object, DatasetStrId::Store),
+            graph_name: map_term(q.graph_name, DatasetStrId::Store),
         })
         .map_err(|e| e.into())
     })
 }
 
+/// Descends into an embedded triple so none of its nested ids are left unmapped.
+fn map_term<I: StrId, J: StrId>(
+    term: EncodedTerm<I>,
+    mapping: impl Fn(I) -> J + Copy,
+) -> EncodedTerm<J> {
+    #[cfg(feature = "rdf-star")]
+    if let EncodedTerm::Triple(triple) = term {
+        return EncodedTerm::Triple(Box::new(EncodedTriple {
+            subject: map_term(triple.subject, mapping),
+            predicate: map_term(triple.predicate, mapping),
+            object: map_term(triple.object, mapping),
+        }));
+    }
+    term.map_id(mapping)
+}
+
+/// Fallible counterpart of `map_term`, failing as soon as a nested id (at any
+/// depth) cannot be mapped, e.g. a `DatasetStrId::Temporary` absent from the store.
+fn try_map_term<I: StrId, J: StrId>(
+    term: EncodedTerm<I>,
+    mapping: impl Fn(I) -> Result<J, ()> + Copy,
+) -> Result<EncodedTerm<J>, ()> {
+    #[cfg(feature = "rdf-star")]
+    if let EncodedTerm::Triple(triple) = term {
+        return Ok(EncodedTerm::Triple(Box::new(EncodedTriple {
+            subject: try_map_term(triple.subject, mapping)?,
+            predicate: try_map_term(triple.predicate, mapping)?,
+            object: try_map_term(triple.object, mapping)?,
+        })));
+    }
+    term.try_map_id(mapping)
+}
+
 type QuadPattern<I> = (
     Option<EncodedTerm<I>>,
     Option<EncodedTerm<I>>,
@@ -235,10 +318,10 @@ fn try_map_quad_pattern<I: StrId>(
     graph_name: Option<EncodedTerm<DatasetStrId<I>>>,
 ) -> Option<QuadPattern<I>> {
     Some((
-        transpose(subject.map(|t| t.try_map_id(unwrap_store_id).ok()))?,
-        transpose(predicate.map(|t| t.try_map_id(unwrap_store_id).ok()))?,
-        transpose(object.map(|t| t.try_map_id(unwrap_store_id).ok()))?,
-        transpose(graph_name.map(|t| t.try_map_id(unwrap_store_id).ok()))?,
+        transpose(subject.map(|t| try_map_term(t, unwrap_store_id).ok()))?,
+        transpose(predicate.map(|t| try_map_term(t, unwrap_store_id).ok()))?,
+        transpose(object.map(|t| try_map_term(t, unwrap_store_id).ok()))?,
+        transpose(graph_name.map(|t| try_map_term(t, unwrap_store_id).ok()))?,
     ))
 }
 
@@ -262,9 +345,7 @@ impl<'a, S: ReadableEncodedStore> StrContainer for &'a DatasetView<S> {
         if let Some(id) = self.store.get_str_id(value).map_err(|e| e.into())? {
             Ok(DatasetStrId::Store(id))
         } else {
-            Ok(DatasetStrId::Temporary(
-                self.extra.borrow_mut().get_or_intern(value),
-            ))
+            Ok(DatasetStrId::Temporary(self.extra.get_or_intern(value)))
         }
     }
 }
@@ -281,3 +362,200 @@ struct EncodedDatasetSpec<I: StrId> {
     default: Vec<EncodedTerm<I>>,
     named: Vec<EncodedTerm<I>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Eq, PartialEq, Debug, Copy, Clone, Hash)]
+    struct TestId(u64);
+
+    impl StrId for TestId {}
+
+    #[derive(Default, Clone)]
+    struct TestStore {
+        quads: Vec<EncodedQuad<TestId>>,
+        strings: Vec<(TestId, String)>,
+    }
+
+    impl StrEncodingAware for TestStore {
+        type Error = EvaluationError;
+        type StrId = TestId;
+    }
+
+    impl StrLookup for TestStore {
+        fn get_str(&self, id: TestId) -> Result<Option<String>, EvaluationError> {
+            Ok(self
+                .strings
+                .iter()
+                .find(|(k, _)| *k == id)
+                .map(|(_, v)| v.clone()))
+        }
+
+        fn get_str_id(&self, value: &str) -> Result<Option<TestId>, EvaluationError> {
+            Ok(self
+                .strings
+                .iter()
+                .find(|(_, v)| v == value)
+                .map(|(k, _)| *k))
+        }
+    }
+
+    impl ReadableEncodedStore for TestStore {
+        type QuadsIter = Box<dyn Iterator<Item = Result<EncodedQuad<TestId>, EvaluationError>>>;
+
+        fn encoded_quads_for_pattern(
+            &self,
+            subject: Option<EncodedTerm<TestId>>,
+            predicate: Option<EncodedTerm<TestId>>,
+            object: Option<EncodedTerm<TestId>>,
+            graph_name: Option<EncodedTerm<TestId>>,
+        ) -> Self::QuadsIter {
+            Box::new(
+                self.quads
+                    .clone()
+                    .into_iter()
+                    .filter(move |q| {
+                        subject.map_or(true, |s| s == q.subject)
+                            && predicate.map_or(true, |p| p == q.predicate)
+                            && object.map_or(true, |o| o == q.object)
+                            && graph_name.map_or(true, |g| g == q.graph_name)
+                    })
+                    .map(Ok),
+            )
+        }
+    }
+
+    fn named_node(id: TestId) -> EncodedTerm<TestId> {
+        EncodedTerm::NamedNode { iri_id: id }
+    }
+
+    fn dataset_view(store: TestStore) -> DatasetView<TestStore> {
+        DatasetView {
+            store,
+            extra: ThreadedRodeo::default(),
+            default_graph_as_union: false,
+            dataset: None,
+        }
+    }
+
+    #[test]
+    fn quoted_triple_with_temporary_term_does_not_match_store_quads() {
+        let embedded_subject = named_node(TestId(1));
+        let embedded_predicate = named_node(TestId(2));
+        let embedded_object = named_node(TestId(3));
+        let store_quad = EncodedQuad::new(
+            EncodedTerm::Triple(Box::new(EncodedTriple {
+                subject: embedded_subject,
+                predicate: embedded_predicate,
+                object: embedded_object,
+            })),
+            named_node(TestId(4)),
+            named_node(TestId(5)),
+            EncodedTerm::DefaultGraph,
+        );
+        let store = TestStore {
+            quads: vec![store_quad],
+            strings: vec![
+                (TestId(1), "urn:s".to_owned()),
+                (TestId(2), "urn:p".to_owned()),
+            ],
+        };
+        let view = dataset_view(store);
+
+        // A pattern rebuilding the exact same triple out of store-backed ids matches.
+        let matching_pattern = Some(EncodedTerm::Triple(Box::new(EncodedTriple {
+            subject: embedded_subject.map_id(DatasetStrId::Store),
+            predicate: embedded_predicate.map_id(DatasetStrId::Store),
+            object: embedded_object.map_id(DatasetStrId::Store),
+        })));
+        assert_eq!(
+            view.encoded_quads_for_pattern(
+                matching_pattern,
+                None,
+                None,
+                Some(EncodedTerm::DefaultGraph)
+            )
+            .count(),
+            1
+        );
+
+        // A pattern whose embedded object is only interned as a Temporary (not present
+        // in the store) can never describe a quad the store could actually contain.
+        let mut view_ref = &view;
+        let temporary_id = view_ref.insert_str("urn:not-in-store").unwrap();
+        assert_eq!(
+            view.get_str(temporary_id).unwrap(),
+            Some("urn:not-in-store".to_owned())
+        );
+        let non_matching_pattern = Some(EncodedTerm::Triple(Box::new(EncodedTriple {
+            subject: embedded_subject.map_id(DatasetStrId::Store),
+            predicate: embedded_predicate.map_id(DatasetStrId::Store),
+            object: EncodedTerm::NamedNode {
+                iri_id: temporary_id,
+            },
+        })));
+        assert_eq!(
+            view.encoded_quads_for_pattern(
+                non_matching_pattern,
+                None,
+                None,
+                Some(EncodedTerm::DefaultGraph)
+            )
+            .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn union_over_named_graphs_returns_quads_from_every_graph() {
+        let graph1 = named_node(TestId(101));
+        let graph2 = named_node(TestId(102));
+        let graph3 = named_node(TestId(103));
+        let store = TestStore {
+            quads: vec![
+                EncodedQuad::new(
+                    named_node(TestId(1)),
+                    named_node(TestId(2)),
+                    named_node(TestId(3)),
+                    graph1,
+                ),
+                EncodedQuad::new(
+                    named_node(TestId(4)),
+                    named_node(TestId(5)),
+                    named_node(TestId(6)),
+                    graph2,
+                ),
+                EncodedQuad::new(
+                    named_node(TestId(7)),
+                    named_node(TestId(8)),
+                    named_node(TestId(9)),
+                    graph3,
+                ),
+            ],
+            strings: Vec::new(),
+        };
+        let view = DatasetView {
+            store,
+            extra: ThreadedRodeo::default(),
+            default_graph_as_union: false,
+            dataset: Some(EncodedDatasetSpec {
+                default: Vec::new(),
+                named: vec![graph1, graph2, graph3],
+            }),
+        };
+
+        let subjects: Vec<_> = view
+            .encoded_quads_for_pattern(None, None, None, None)
+            .map(|q| q.unwrap().subject)
+            .collect();
+        assert_eq!(
+            subjects,
+            vec![
+                named_node(TestId(1)).map_id(DatasetStrId::Store),
+                named_node(TestId(4)).map_id(DatasetStrId::Store),
+                named_node(TestId(7)).map_id(DatasetStrId::Store),
+            ]
+        );
+    }
+}